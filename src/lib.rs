@@ -3,29 +3,82 @@
 #[cfg(feature = "util")]
 pub mod util;
 
-use std::time::Duration;
+use std::{
+    collections::{HashMap, VecDeque},
+    hash::Hash,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
 
 use async_trait::async_trait;
 use futures::{stream::FuturesUnordered, StreamExt};
 use influxdb::InfluxDbWriteable;
-use tokio::{sync::mpsc, time::MissedTickBehavior};
+use rand::Rng;
+use tokio::{
+    sync::{oneshot, Semaphore},
+    time::MissedTickBehavior,
+};
+
+/// A metric with a settable `time` field, as required by `#[derive(InfluxDbWriteable)]`. Needed by
+/// [`IntoNamedQuery::into_named_query_at`]: this crate's [`influxdb::WriteQuery`] has no public
+/// `time` field or precision setter of its own (precision is just whichever
+/// [`influxdb::Timestamp`] variant was used to build it), so an explicit timestamp has to be set
+/// on the metric itself before conversion, rather than mutated into an already-built query.
+pub trait WithTimestamp {
+    /// Replace this metric's timestamp.
+    fn with_timestamp(self, timestamp: influxdb::Timestamp) -> Self;
+}
 
 /// Convert a metric to an [influxdb] query using the type name.
 pub trait IntoNamedQuery: InfluxDbWriteable + Sized {
+    /// Convert using the metric's own timestamp.
     fn into_named_query(self) -> influxdb::WriteQuery {
-        let type_name = std::any::type_name::<Self>();
-
-        let name = type_name
-            .rsplit_once("::")
-            .map(|(_, name)| name)
-            .unwrap_or(type_name);
+        named_query(self)
+    }
 
-        InfluxDbWriteable::into_query(self, name)
+    /// Convert after replacing the metric's timestamp with `timestamp`, rather than whatever time
+    /// the metric itself carries. Useful when a [`MetricsConsumer`] buffers points across a
+    /// multi-second flush interval: without this, every batched point would collapse onto the
+    /// flush instant instead of the instant it was actually observed. Precision is whatever
+    /// `timestamp`'s variant implies (`Nanoseconds`, `Seconds`, ...); there's no separate
+    /// precision knob in this crate.
+    fn into_named_query_at(self, timestamp: influxdb::Timestamp) -> influxdb::WriteQuery
+    where
+        Self: WithTimestamp,
+    {
+        named_query(self.with_timestamp(timestamp))
     }
 }
 
+/// Shared implementation backing [`IntoNamedQuery`]'s conversions: build the query using the
+/// type name as the series name.
+fn named_query<T: InfluxDbWriteable>(metric: T) -> influxdb::WriteQuery {
+    let type_name = std::any::type_name::<T>();
+
+    let name = type_name
+        .rsplit_once("::")
+        .map(|(_, name)| name)
+        .unwrap_or(type_name);
+
+    InfluxDbWriteable::into_query(metric, name)
+}
+
 impl<T: InfluxDbWriteable> IntoNamedQuery for T {}
 
+/// The current time as a nanosecond-precision [`influxdb::Timestamp`]. Used as a fallback
+/// timestamp by anything that doesn't track its own, e.g. [`Accumulator::timestamp`]'s default.
+pub(crate) fn now_timestamp() -> influxdb::Timestamp {
+    let nanos = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos();
+
+    influxdb::Timestamp::Nanoseconds(nanos)
+}
+
 /// Dispatch a single metric to the database.
 /// Will emmit a log record if an error occurs.
 pub async fn dispatch(client: &influxdb::Client, metric: influxdb::WriteQuery) {
@@ -48,6 +101,96 @@ where
         .await;
 }
 
+/// Retry policy for [`dispatch_with_retry`]: retries transient failures with exponential backoff
+/// and jitter, up to `max_attempts` total attempts, then gives up.
+#[derive(Debug, Clone, Copy)]
+pub struct RetryPolicy {
+    /// Maximum number of attempts, including the first, before giving up.
+    pub max_attempts: u32,
+    /// Delay before the first retry; doubles on each subsequent retry.
+    pub base_delay: Duration,
+    /// Upper bound for the backoff delay, before jitter is added.
+    pub cap: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 5,
+            base_delay: Duration::from_millis(100),
+            cap: Duration::from_secs(10),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Backoff delay for the given (zero-indexed) attempt: `min(base * 2^attempt, cap)` plus
+    /// random jitter in `[0, delay / 2)`.
+    fn backoff(&self, attempt: u32) -> Duration {
+        let exponential = self
+            .base_delay
+            .checked_mul(1u32.checked_shl(attempt).unwrap_or(u32::MAX))
+            .unwrap_or(self.cap);
+        let delay = exponential.min(self.cap);
+
+        let jitter = delay.mul_f64(rand::thread_rng().gen_range(0.0..0.5));
+        delay + jitter
+    }
+}
+
+/// Whether an InfluxDB error is worth retrying. Connection/timeout issues are transient; errors
+/// where the server understood and rejected the request (e.g. a malformed query) are permanent
+/// and retrying them would just waste the remaining attempts.
+fn is_retryable(error: &influxdb::Error) -> bool {
+    matches!(error, influxdb::Error::ConnectionError { .. })
+}
+
+/// Dispatch a single metric to the database, retrying transient failures per `policy` with
+/// exponential backoff instead of discarding the metric on the first error. Gives up and
+/// increments `failures` once `policy.max_attempts` is exhausted.
+pub async fn dispatch_with_retry(
+    client: &influxdb::Client,
+    metric: influxdb::WriteQuery,
+    policy: &RetryPolicy,
+    failures: &AtomicU64,
+) {
+    for attempt in 0..policy.max_attempts {
+        let result = client.query(metric.clone()).await;
+
+        match result {
+            Ok(_) => return,
+
+            Err(error) if attempt + 1 < policy.max_attempts && is_retryable(&error) => {
+                tracing::warn!("Retrying metric submission after error: {}", error);
+                tokio::time::sleep(policy.backoff(attempt)).await;
+            }
+
+            Err(error) => {
+                tracing::error!("Failed to submit metric: {}", error);
+                failures.fetch_add(1, Ordering::Relaxed);
+                return;
+            }
+        }
+    }
+}
+
+/// Dispatch many metrics to the database with [`dispatch_with_retry`], concurrently.
+pub async fn dispatch_many_with_retry<I>(
+    client: &influxdb::Client,
+    metrics: I,
+    policy: &RetryPolicy,
+    failures: &AtomicU64,
+) where
+    I: IntoIterator<Item = influxdb::WriteQuery>,
+{
+    metrics
+        .into_iter()
+        .map(|metric| dispatch_with_retry(client, metric, policy, failures))
+        .collect::<FuturesUnordered<_>>()
+        .collect::<()>()
+        .await;
+}
+
 /// Aggregator for metrics.
 /// An aggregator should collect metrics so they can be batch dispatched.
 #[async_trait]
@@ -65,12 +208,251 @@ pub trait MetricsConsumer {
     async fn flush(&mut self);
 }
 
+/// A metric that can be grouped into a series/tag bucket for aggregation by [`KeyedAggregator`].
+pub trait Keyed {
+    /// The bucket key, e.g. a combination of series name and time bucket.
+    type Key: Eq + Hash;
+
+    /// The bucket this metric belongs to.
+    fn key(&self) -> Self::Key;
+}
+
+/// An accumulator that folds repeated observations of a [`Keyed`] metric into a single value,
+/// e.g. summing counts or merging running averages.
+pub trait Accumulator<M>: Default {
+    /// Merge one more observation into this bucket.
+    fn accumulate(&mut self, metric: M);
+
+    /// The timestamp to stamp the flushed point with, e.g. the time of the first observation
+    /// folded into this bucket. Defaults to the flush instant, which is fine for accumulators
+    /// that don't track one, but means every point in a bucket collapses onto the same instant
+    /// regardless of when it was actually observed; see [`IntoNamedQuery::into_named_query_at`].
+    fn timestamp(&self) -> influxdb::Timestamp {
+        now_timestamp()
+    }
+}
+
+/// A [`MetricsConsumer`] that batches metrics by [`Keyed::key`] into an [`Accumulator`] and
+/// flushes one [`influxdb::WriteQuery`] per bucket, instead of one per raw point. This collapses
+/// thousands of points per interval into a single row per series.
+#[derive(Debug)]
+pub struct KeyedAggregator<M: Keyed, V> {
+    client: influxdb::Client,
+    buckets: HashMap<M::Key, V>,
+}
+
+#[async_trait]
+impl<M, V> MetricsConsumer for KeyedAggregator<M, V>
+where
+    M: Keyed + Send + 'static,
+    M::Key: Send,
+    V: Accumulator<M> + WithTimestamp + IntoNamedQuery + Send + 'static,
+{
+    type Metric = M;
+
+    fn new(client: influxdb::Client) -> Self {
+        Self {
+            client,
+            buckets: HashMap::new(),
+        }
+    }
+
+    fn accept(&mut self, metric: M) {
+        let key = metric.key();
+        self.buckets.entry(key).or_default().accumulate(metric);
+    }
+
+    async fn flush(&mut self) {
+        let queries = self.buckets.drain().map(|(_, accumulator)| {
+            let timestamp = accumulator.timestamp();
+            accumulator.into_named_query_at(timestamp)
+        });
+
+        dispatch_many(&self.client, queries).await;
+    }
+}
+
+/// Overflow behavior for [`InfluxDbHandle`] when the buffer is full.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OverflowPolicy {
+    /// Discard the incoming metric and record it as dropped. This is the previous, and still
+    /// default, behavior.
+    #[default]
+    Drop,
+    /// Wait for buffer space before admitting the metric. Only [`InfluxDbHandle::submit_await`]
+    /// can actually wait; [`InfluxDbHandle::submit`] is synchronous and falls back to `Drop`.
+    Block,
+    /// Evict the oldest buffered metric to make room for the incoming one. The evicted metric is
+    /// counted as dropped.
+    DropOldest,
+}
+
+/// Sampling strategy applied to incoming metrics in [`InfluxDbHandle::submit`]/
+/// [`InfluxDbHandle::submit_await`], before they ever reach the inbox. This bounds both the
+/// inbox's channel pressure and InfluxDB write volume for very high-frequency metrics, without
+/// abandoning the metric entirely: a sampled-out metric is never admitted in the first place, so
+/// it can't also trip an [`OverflowPolicy`] drop.
+#[derive(Debug, Clone, Default)]
+pub enum SamplingStrategy {
+    /// Admit every metric (default).
+    #[default]
+    None,
+    /// Admit each metric independently with probability `probability`. This does not rescale
+    /// anything on the caller's behalf: whoever picks `probability` here already knows its value
+    /// (it was passed to [`InfluxDbHandle::with_sampling`]), and can retrieve it again via
+    /// [`InfluxDbHandle::sampling_strategy`] to scale accepted counts by `1 / probability` when
+    /// building a metric, if unbiased totals are needed.
+    Fixed { probability: f64 },
+    /// Keep at most `capacity` metrics per flush window, chosen uniformly at random from the
+    /// metrics seen during that window via reservoir sampling (Algorithm R): the reservoir fills
+    /// with the first `capacity` metrics, then each later metric replaces a uniformly random slot
+    /// with probability `capacity / i`, where `i` counts every metric seen so far in the window
+    /// (the first `capacity` included). The reservoir is handed to the
+    /// [`MetricsConsumer`] once per flush window, alongside everything else admitted that window.
+    Reservoir { capacity: usize },
+}
+
+/// Shared state for [`SamplingStrategy::Reservoir`]: filled by [`InfluxDbHandle::submit`]/
+/// [`InfluxDbHandle::submit_await`] and drained by the push loop once per flush window.
+#[derive(Debug)]
+struct Reservoir<M> {
+    capacity: usize,
+    items: Vec<M>,
+    /// Count of metrics offered so far in the current window.
+    seen: usize,
+}
+
+impl<M> Reservoir<M> {
+    fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            items: Vec::with_capacity(capacity),
+            seen: 0,
+        }
+    }
+
+    /// Offer a metric to the reservoir for the current window.
+    fn offer(&mut self, metric: M) {
+        self.seen += 1;
+
+        if self.items.len() < self.capacity {
+            self.items.push(metric);
+        } else {
+            let slot = rand::thread_rng().gen_range(0..self.seen);
+            if slot < self.capacity {
+                self.items[slot] = metric;
+            }
+        }
+    }
+
+    /// Take everything sampled into the current window, and start a new one.
+    fn take_window(&mut self) -> Vec<M> {
+        self.seen = 0;
+        std::mem::take(&mut self.items)
+    }
+}
+
+/// A bounded multi-producer single-consumer queue of metrics.
+/// Built on a pair of [`Semaphore`]s instead of [`tokio::sync::mpsc`] so that, unlike `mpsc`, it
+/// can evict its oldest buffered item to make room for a new one (see [`OverflowPolicy::DropOldest`]).
+#[derive(Debug)]
+struct Inbox<M> {
+    buffer: Mutex<VecDeque<M>>,
+    space: Semaphore,
+    items: Semaphore,
+}
+
+impl<M> Inbox<M> {
+    fn new(capacity: usize) -> Self {
+        Self {
+            buffer: Mutex::new(VecDeque::with_capacity(capacity)),
+            space: Semaphore::new(capacity),
+            items: Semaphore::new(0),
+        }
+    }
+
+    /// Push without waiting. Returns the metric back if the buffer is full.
+    fn try_push(&self, metric: M) -> Result<(), M> {
+        let Ok(permit) = self.space.try_acquire() else {
+            return Err(metric);
+        };
+        permit.forget();
+
+        self.buffer.lock().unwrap().push_back(metric);
+        self.items.add_permits(1);
+        Ok(())
+    }
+
+    /// Push, evicting the oldest buffered metric if the buffer is full.
+    /// Returns whichever metric ended up dropped to make room (normally the evicted oldest entry,
+    /// but the new metric itself if the buffer was already empty), or `None` if the metric was
+    /// admitted without dropping anything.
+    fn push_evicting_oldest(&self, metric: M) -> Option<M> {
+        let Err(metric) = self.try_push(metric) else {
+            return None;
+        };
+
+        // Buffer is full: swap the oldest entry out for the new one. Space and item counts are
+        // unaffected since one item leaves and another takes its slot.
+        let mut buffer = self.buffer.lock().unwrap();
+        match buffer.pop_front() {
+            Some(evicted) => {
+                buffer.push_back(metric);
+                Some(evicted)
+            }
+            // Nothing buffered to evict despite the inbox reporting full (e.g. zero capacity):
+            // there's no room to admit the new metric either. Drop it instead of pushing it in
+            // without a matching items permit, which would leak it forever (pop() would never
+            // see it) without even counting it as dropped.
+            None => Some(metric),
+        }
+    }
+
+    /// Push, waiting for space to become available.
+    async fn push(&self, metric: M) {
+        let permit = self.space.acquire().await.expect("space semaphore is never closed");
+        permit.forget();
+
+        self.buffer.lock().unwrap().push_back(metric);
+        self.items.add_permits(1);
+    }
+
+    /// Pop the oldest metric, waiting until one is available.
+    async fn pop(&self) -> M {
+        let permit = self.items.acquire().await.expect("items semaphore is never closed");
+        permit.forget();
+
+        let metric = self.buffer.lock().unwrap().pop_front().expect("items permit implies an entry");
+        self.space.add_permits(1);
+        metric
+    }
+
+    /// Drain every currently buffered metric, in order, bypassing the semaphores. Used on
+    /// shutdown where we want everything that is already in the buffer without waiting for more
+    /// to arrive.
+    fn drain(&self) -> VecDeque<M> {
+        std::mem::take(&mut *self.buffer.lock().unwrap())
+    }
+}
+
 /// A handle to the InfluxDb metrics recorder.
-/// Aborts the submission task when dropped.
+/// Aborts the submission task when dropped, discarding anything not yet flushed. Call
+/// [`InfluxDbHandle::shutdown`] instead if the last batch of metrics must be flushed before the
+/// process exits.
 #[derive(Debug)]
 pub struct InfluxDbHandle<M> {
-    /// The channel for submitting metrics.
-    channel: mpsc::Sender<M>,
+    /// The inbox metrics are submitted into before being picked up by the push loop.
+    inbox: Arc<Inbox<M>>,
+    /// How to handle a full inbox.
+    overflow: OverflowPolicy,
+    /// How to sample incoming metrics before they reach the inbox.
+    sampling: SamplingStrategy,
+    /// Shared reservoir state, present only under [`SamplingStrategy::Reservoir`].
+    reservoir: Option<Arc<Mutex<Reservoir<M>>>>,
+    /// Count of metrics discarded because the inbox was full.
+    dropped: Arc<AtomicU64>,
+    /// The channel for requesting a graceful shutdown of the push loop.
+    shutdown: Option<oneshot::Sender<oneshot::Sender<()>>>,
     /// The metrics task, which consumes the metrics in the channel and submits them in an
     /// infinite loop.
     metrics_task: tokio::task::JoinHandle<()>,
@@ -86,37 +468,178 @@ impl<M> InfluxDbHandle<M>
 where
     M: Send + 'static,
 {
-    /// Start the metrics task.
+    /// Start the metrics task with the default [`OverflowPolicy::Drop`] and no sampling.
     /// This task will run indefinitely, but will be aborted when the handle is dropped.
     pub fn new<C>(consumer: C, push_interval: u64, buffer_size: usize) -> Self
     where
         C: MetricsConsumer<Metric = M> + Send + 'static,
     {
-        let (tx, rx) = mpsc::channel(buffer_size);
+        Self::with_overflow_policy(consumer, push_interval, buffer_size, OverflowPolicy::default())
+    }
 
-        let task = Self::push_loop(consumer, rx, push_interval);
+    /// Start the metrics task with an explicit [`OverflowPolicy`] and no sampling.
+    pub fn with_overflow_policy<C>(
+        consumer: C,
+        push_interval: u64,
+        buffer_size: usize,
+        overflow: OverflowPolicy,
+    ) -> Self
+    where
+        C: MetricsConsumer<Metric = M> + Send + 'static,
+    {
+        Self::with_sampling(consumer, push_interval, buffer_size, overflow, SamplingStrategy::default())
+    }
+
+    /// Start the metrics task with an explicit [`OverflowPolicy`] and [`SamplingStrategy`].
+    pub fn with_sampling<C>(
+        consumer: C,
+        push_interval: u64,
+        buffer_size: usize,
+        overflow: OverflowPolicy,
+        sampling: SamplingStrategy,
+    ) -> Self
+    where
+        C: MetricsConsumer<Metric = M> + Send + 'static,
+    {
+        let inbox = Arc::new(Inbox::new(buffer_size));
+        let dropped = Arc::new(AtomicU64::new(0));
+        let (shutdown_tx, shutdown_rx) = oneshot::channel();
+
+        let reservoir = match &sampling {
+            SamplingStrategy::Reservoir { capacity } => Some(Arc::new(Mutex::new(Reservoir::new(*capacity)))),
+            SamplingStrategy::None | SamplingStrategy::Fixed { .. } => None,
+        };
+
+        let task = Self::push_loop(
+            consumer,
+            Arc::clone(&inbox),
+            push_interval,
+            shutdown_rx,
+            reservoir.clone(),
+        );
 
         Self {
-            channel: tx,
+            inbox,
+            overflow,
+            sampling,
+            reservoir,
+            dropped,
+            shutdown: Some(shutdown_tx),
             metrics_task: tokio::task::spawn(task),
         }
     }
 
+    /// The [`SamplingStrategy`] this handle was constructed with.
+    pub fn sampling_strategy(&self) -> &SamplingStrategy {
+        &self.sampling
+    }
+
+    /// Apply [`Self::sampling`] to `metric`, returning it back if it should be admitted to the
+    /// inbox. A `None` result means the metric was sampled out (or, for
+    /// [`SamplingStrategy::Reservoir`], already handed off to the reservoir) and `submit`/
+    /// `submit_await` should do nothing further with it.
+    fn sample(&self, metric: M) -> Option<M> {
+        match &self.sampling {
+            SamplingStrategy::None => Some(metric),
+
+            SamplingStrategy::Fixed { probability } => {
+                rand::thread_rng().gen_bool(probability.clamp(0.0, 1.0)).then_some(metric)
+            }
+
+            SamplingStrategy::Reservoir { .. } => {
+                if let Some(reservoir) = &self.reservoir {
+                    reservoir.lock().unwrap().offer(metric);
+                }
+                None
+            }
+        }
+    }
+
     /// Submit a metric.
-    /// There is no strong guarantee that the metric will be recorded. It may actually be
-    /// discarded if we're struggling to dispatch all metrics.
+    /// There is no strong guarantee that the metric will be recorded: it may be sampled out per
+    /// [`Self::sampling_strategy`], or under [`OverflowPolicy::Drop`] or [`OverflowPolicy::Block`]
+    /// (which this synchronous method cannot actually wait on) it may be discarded if we're
+    /// struggling to dispatch all metrics. Use [`InfluxDbHandle::submit_await`] for guaranteed
+    /// delivery under backpressure.
     pub fn submit(&self, metric: M) {
-        if let Err(error) = self.channel.try_send(metric) {
-            tracing::error!("Failed to submit metric: {}", error);
+        let Some(metric) = self.sample(metric) else {
+            return;
+        };
+
+        let rejected = match self.overflow {
+            OverflowPolicy::DropOldest => self.inbox.push_evicting_oldest(metric),
+            OverflowPolicy::Drop | OverflowPolicy::Block => self.inbox.try_push(metric).err(),
+        };
+
+        if rejected.is_some() {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+            tracing::error!("Failed to submit metric: inbox is full");
+        }
+    }
+
+    /// Submit a metric, honoring [`OverflowPolicy::Block`] by waiting for buffer space instead of
+    /// discarding the metric. Use this for guaranteed delivery at the cost of applying
+    /// backpressure to the caller. As with [`InfluxDbHandle::submit`], the metric may still be
+    /// sampled out per [`Self::sampling_strategy`] before it ever reaches the inbox.
+    pub async fn submit_await(&self, metric: M) {
+        let Some(metric) = self.sample(metric) else {
+            return;
+        };
+
+        match self.overflow {
+            OverflowPolicy::Block => self.inbox.push(metric).await,
+
+            OverflowPolicy::DropOldest => {
+                if self.inbox.push_evicting_oldest(metric).is_some() {
+                    self.dropped.fetch_add(1, Ordering::Relaxed);
+                }
+            }
+
+            OverflowPolicy::Drop => {
+                if self.inbox.try_push(metric).is_err() {
+                    self.dropped.fetch_add(1, Ordering::Relaxed);
+                    tracing::error!("Failed to submit metric: inbox is full");
+                }
+            }
+        }
+    }
+
+    /// Count of metrics discarded so far because the inbox was full.
+    pub fn dropped_count(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    /// Gracefully shut down the metrics task.
+    /// Consumes the handle so nothing more can be submitted, drains anything already buffered
+    /// (in the inbox and, under [`SamplingStrategy::Reservoir`], the reservoir) into the consumer, and
+    /// awaits one last [`MetricsConsumer::flush`] before returning. Use this instead of simply
+    /// dropping the handle whenever the last batch of metrics matters, e.g. in CLI tools or test
+    /// harnesses that exit right after submitting their final metric.
+    pub async fn shutdown(mut self) {
+        let Some(shutdown) = self.shutdown.take() else {
+            return;
+        };
+
+        let (ack_tx, ack_rx) = oneshot::channel();
+
+        if shutdown.send(ack_tx).is_err() {
+            return; // Push loop already gone, nothing left to flush.
         }
+
+        let _ = ack_rx.await;
     }
 
     /// InfluxDb push loop.
     /// This function will run indefinitely, so it must be placed inside a task so that it can
     /// be aborted when we're done.
-    #[tracing::instrument(skip(consumer, channel))]
-    async fn push_loop<C>(mut consumer: C, mut channel: mpsc::Receiver<M>, push_interval: u64)
-    where
+    #[tracing::instrument(skip(consumer, inbox, shutdown, reservoir))]
+    async fn push_loop<C>(
+        mut consumer: C,
+        inbox: Arc<Inbox<M>>,
+        push_interval: u64,
+        shutdown: oneshot::Receiver<oneshot::Sender<()>>,
+        reservoir: Option<Arc<Mutex<Reservoir<M>>>>,
+    ) where
         C: MetricsConsumer<Metric = M>,
     {
         let mut interval = tokio::time::interval(Duration::from_secs(push_interval));
@@ -124,15 +647,347 @@ where
 
         tracing::info!("Starting InfluxDb metrics loop");
 
+        tokio::pin!(shutdown);
+
         loop {
             tokio::select! {
-                result = channel.recv() => match result {
-                    None => break, // Channel is closed, abort metrics task.
-                    Some(metric) => consumer.accept(metric),
+                // Sampling already happened in `submit`/`submit_await`; anything in the inbox is
+                // admitted as-is.
+                metric = inbox.pop() => consumer.accept(metric),
+
+                _ = interval.tick() => {
+                    if let Some(reservoir) = &reservoir {
+                        for metric in reservoir.lock().unwrap().take_window() {
+                            consumer.accept(metric);
+                        }
+                    }
+
+                    consumer.flush().await;
                 },
 
-                _ = interval.tick() => consumer.flush().await,
+                ack = &mut shutdown => {
+                    for metric in inbox.drain() {
+                        consumer.accept(metric);
+                    }
+
+                    if let Some(reservoir) = &reservoir {
+                        for metric in reservoir.lock().unwrap().take_window() {
+                            consumer.accept(metric);
+                        }
+                    }
+
+                    consumer.flush().await;
+
+                    if let Ok(ack) = ack {
+                        let _ = ack.send(());
+                    }
+
+                    return;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::AtomicUsize;
+
+    use super::*;
+
+    /// A [`MetricsConsumer`] that just records everything it's given, for inspection from the
+    /// test that spawned it.
+    struct RecordingConsumer {
+        accepted: Arc<Mutex<Vec<u64>>>,
+        flushes: Arc<AtomicUsize>,
+    }
+
+    impl RecordingConsumer {
+        /// Build a consumer along with handles to its shared state, since the consumer itself is
+        /// moved into the handle's push loop task.
+        fn new_test() -> (Self, Arc<Mutex<Vec<u64>>>, Arc<AtomicUsize>) {
+            let accepted = Arc::new(Mutex::new(Vec::new()));
+            let flushes = Arc::new(AtomicUsize::new(0));
+
+            let consumer = Self {
+                accepted: Arc::clone(&accepted),
+                flushes: Arc::clone(&flushes),
+            };
+
+            (consumer, accepted, flushes)
+        }
+    }
+
+    #[async_trait]
+    impl MetricsConsumer for RecordingConsumer {
+        type Metric = u64;
+
+        fn new(_client: influxdb::Client) -> Self {
+            Self::new_test().0
+        }
+
+        fn accept(&mut self, metric: u64) {
+            self.accepted.lock().unwrap().push(metric);
+        }
+
+        async fn flush(&mut self) {
+            self.flushes.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_flushes_buffered_metrics() {
+        let (consumer, accepted, flushes) = RecordingConsumer::new_test();
+        // Long push interval: the interval's first tick is immediate, so one flush happens at
+        // startup; the only other flush we expect is the one triggered by shutdown.
+        let handle = InfluxDbHandle::new(consumer, 3600, 16);
+
+        // Let the push loop start and take its immediate first tick before submitting.
+        tokio::task::yield_now().await;
+
+        for metric in 0..5 {
+            handle.submit(metric);
+        }
+
+        handle.shutdown().await;
+
+        assert_eq!(*accepted.lock().unwrap(), vec![0, 1, 2, 3, 4]);
+        assert_eq!(flushes.load(Ordering::Relaxed), 2);
+    }
+
+    #[test]
+    fn test_inbox_try_push_rejects_when_full() {
+        let inbox = Inbox::new(2);
+
+        assert_eq!(inbox.try_push(1u64), Ok(()));
+        assert_eq!(inbox.try_push(2u64), Ok(()));
+        assert_eq!(inbox.try_push(3u64), Err(3));
+    }
+
+    #[tokio::test]
+    async fn test_inbox_push_evicting_oldest() {
+        let inbox = Inbox::new(2);
+
+        assert_eq!(inbox.try_push(1u64), Ok(()));
+        assert_eq!(inbox.try_push(2u64), Ok(()));
+
+        // Buffer is full: the oldest entry (1) is evicted to make room for 3.
+        assert_eq!(inbox.push_evicting_oldest(3), Some(1));
+
+        assert_eq!(inbox.pop().await, 2);
+        assert_eq!(inbox.pop().await, 3);
+    }
+
+    #[test]
+    fn test_inbox_push_evicting_oldest_drops_when_nothing_to_evict() {
+        // Zero capacity: the buffer is always empty, so there's never an oldest entry to evict.
+        let inbox: Inbox<u64> = Inbox::new(0);
+
+        // The new metric must be dropped and reported as such, not silently swallowed.
+        assert_eq!(inbox.push_evicting_oldest(1), Some(1));
+    }
+
+    #[tokio::test]
+    async fn test_drop_policy_counts_drops_when_inbox_is_full() {
+        let (consumer, _, _) = RecordingConsumer::new_test();
+        // Zero capacity: every submission finds the inbox full and is dropped, regardless of how
+        // fast the push loop happens to drain it.
+        let handle = InfluxDbHandle::with_overflow_policy(consumer, 3600, 0, OverflowPolicy::Drop);
+
+        handle.submit(1);
+        handle.submit(2);
+        handle.submit(3);
+
+        assert_eq!(handle.dropped_count(), 3);
+    }
+
+    struct CountMetric {
+        series: &'static str,
+    }
+
+    impl Keyed for CountMetric {
+        type Key = &'static str;
+
+        fn key(&self) -> Self::Key {
+            self.series
+        }
+    }
+
+    #[derive(Debug, Clone, Copy, InfluxDbWriteable)]
+    struct CountFields {
+        time: influxdb::Timestamp,
+        count: u64,
+    }
+
+    impl Default for CountFields {
+        fn default() -> Self {
+            Self {
+                time: now_timestamp(),
+                count: 0,
             }
         }
     }
+
+    impl Accumulator<CountMetric> for CountFields {
+        fn accumulate(&mut self, _metric: CountMetric) {
+            self.count += 1;
+        }
+    }
+
+    impl WithTimestamp for CountFields {
+        fn with_timestamp(self, timestamp: influxdb::Timestamp) -> Self {
+            Self { time: timestamp, ..self }
+        }
+    }
+
+    #[test]
+    fn test_keyed_aggregator_merges_by_key() {
+        let client = influxdb::Client::new("http://localhost:0", "test");
+        let mut aggregator: KeyedAggregator<CountMetric, CountFields> = KeyedAggregator::new(client);
+
+        aggregator.accept(CountMetric { series: "a" });
+        aggregator.accept(CountMetric { series: "a" });
+        aggregator.accept(CountMetric { series: "b" });
+
+        assert_eq!(aggregator.buckets[&"a"].count, 2);
+        assert_eq!(aggregator.buckets[&"b"].count, 1);
+        assert_eq!(aggregator.buckets.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_fixed_sampling_zero_probability_drops_everything() {
+        let (consumer, accepted, _) = RecordingConsumer::new_test();
+        let handle = InfluxDbHandle::with_sampling(
+            consumer,
+            3600,
+            16,
+            OverflowPolicy::default(),
+            SamplingStrategy::Fixed { probability: 0.0 },
+        );
+
+        for metric in 0..20 {
+            handle.submit(metric);
+        }
+
+        handle.shutdown().await;
+
+        assert!(accepted.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_fixed_sampling_full_probability_keeps_everything() {
+        let (consumer, accepted, _) = RecordingConsumer::new_test();
+        let handle = InfluxDbHandle::with_sampling(
+            consumer,
+            3600,
+            16,
+            OverflowPolicy::default(),
+            SamplingStrategy::Fixed { probability: 1.0 },
+        );
+
+        for metric in 0..5 {
+            handle.submit(metric);
+        }
+
+        handle.shutdown().await;
+
+        assert_eq!(*accepted.lock().unwrap(), vec![0, 1, 2, 3, 4]);
+    }
+
+    #[tokio::test]
+    async fn test_reservoir_sampling_caps_window_to_capacity() {
+        let (consumer, accepted, _) = RecordingConsumer::new_test();
+        let handle = InfluxDbHandle::with_sampling(
+            consumer,
+            3600,
+            32,
+            OverflowPolicy::default(),
+            SamplingStrategy::Reservoir { capacity: 3 },
+        );
+
+        for metric in 0..20 {
+            handle.submit(metric);
+        }
+
+        handle.shutdown().await;
+
+        // The whole submission burst falls into a single flush window, so the reservoir should
+        // have capped it at `capacity` regardless of how many metrics were actually offered.
+        assert_eq!(accepted.lock().unwrap().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_sampling_strategy_accessor_reflects_configuration() {
+        let (consumer, _, _) = RecordingConsumer::new_test();
+        let handle = InfluxDbHandle::with_sampling(
+            consumer,
+            3600,
+            16,
+            OverflowPolicy::default(),
+            SamplingStrategy::Fixed { probability: 0.25 },
+        );
+
+        assert!(matches!(
+            handle.sampling_strategy(),
+            SamplingStrategy::Fixed { probability } if (*probability - 0.25).abs() < f64::EPSILON
+        ));
+    }
+
+    #[test]
+    fn test_retry_policy_backoff_grows_exponentially() {
+        let policy = RetryPolicy {
+            max_attempts: 10,
+            base_delay: Duration::from_millis(100),
+            cap: Duration::from_secs(10),
+        };
+
+        for attempt in 0..5 {
+            let exponential = policy.base_delay * 2u32.pow(attempt);
+            let delay = policy.backoff(attempt);
+
+            // Jitter adds up to half the exponential delay on top.
+            assert!(delay >= exponential, "attempt {attempt}: {delay:?} < {exponential:?}");
+            assert!(delay <= exponential.mul_f64(1.5), "attempt {attempt}: {delay:?} > 1.5x {exponential:?}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_dispatch_with_retry_retries_then_gives_up_on_connection_errors() {
+        // Nothing listens on this port, so every attempt fails with a transient
+        // `influxdb::Error::ConnectionError`, and `dispatch_with_retry` should retry up to
+        // `max_attempts` times before giving up and counting a failure.
+        let client = influxdb::Client::new("http://localhost:1", "test");
+        let query = CountFields::default().into_named_query();
+        let policy = RetryPolicy {
+            max_attempts: 3,
+            base_delay: Duration::from_millis(1),
+            cap: Duration::from_millis(10),
+        };
+        let failures = AtomicU64::new(0);
+
+        let start = tokio::time::Instant::now();
+        dispatch_with_retry(&client, query, &policy, &failures).await;
+        let elapsed = start.elapsed();
+
+        // One failure counted after exhausting all attempts, not one per attempt.
+        assert_eq!(failures.load(Ordering::Relaxed), 1);
+        // Backoff slept between the 2 retries, so this took noticeably longer than a single
+        // immediate failure would have.
+        assert!(elapsed >= Duration::from_millis(2), "{elapsed:?}");
+    }
+
+    #[test]
+    fn test_retry_policy_backoff_respects_cap() {
+        let policy = RetryPolicy {
+            max_attempts: 10,
+            base_delay: Duration::from_millis(100),
+            cap: Duration::from_secs(1),
+        };
+
+        // Attempt high enough that the uncapped exponential delay would vastly exceed `cap`.
+        let delay = policy.backoff(20);
+
+        assert!(delay >= policy.cap, "{delay:?} < {:?}", policy.cap);
+        assert!(delay <= policy.cap.mul_f64(1.5), "{delay:?} > 1.5x {:?}", policy.cap);
+    }
 }
@@ -1,5 +1,9 @@
 //! Utilities for aggregating metrics.
 
+use influxdb::InfluxDbWriteable;
+
+use crate::IntoNamedQuery;
+
 /// The average of a stream of values.
 /// This is a compact representation, and will not store all elements. This comes at a
 /// small precision cost, which should be negligible for metrics.
@@ -36,6 +40,245 @@ impl RunningAverage {
     }
 }
 
+/// Running mean, variance, standard deviation, min and max of a stream of values, computed in a
+/// single numerically-stable pass using Welford's online algorithm. Unlike a naive
+/// sum-of-squares, this avoids catastrophic cancellation, at a similarly small memory cost to
+/// [`RunningAverage`].
+#[derive(Debug, Default)]
+pub struct RunningStats {
+    count: u64,
+    mean: f64,
+    /// Sum of squares of differences from the current mean.
+    m2: f64,
+    min: f64,
+    max: f64,
+}
+
+impl RunningStats {
+    /// Accept a new value from the input stream.
+    pub fn accept(&mut self, value: f64) {
+        self.count += 1;
+
+        let delta = value - self.mean;
+        self.mean += delta / self.count as f64;
+        self.m2 += delta * (value - self.mean);
+
+        self.min = if self.count == 1 { value } else { self.min.min(value) };
+        self.max = if self.count == 1 { value } else { self.max.max(value) };
+    }
+
+    /// Get the current mean.
+    pub fn mean(&self) -> f64 {
+        self.mean
+    }
+
+    /// Get the current variance. `0.0` if fewer than one value has been recorded.
+    pub fn variance(&self) -> f64 {
+        if self.count == 0 {
+            0.0
+        } else {
+            self.m2 / self.count as f64
+        }
+    }
+
+    /// Get the current standard deviation. `0.0` if fewer than one value has been recorded.
+    pub fn stddev(&self) -> f64 {
+        self.variance().sqrt()
+    }
+
+    /// Get the smallest recorded value. `0.0` if nothing has been recorded.
+    pub fn min(&self) -> f64 {
+        self.min
+    }
+
+    /// Get the largest recorded value. `0.0` if nothing has been recorded.
+    pub fn max(&self) -> f64 {
+        self.max
+    }
+
+    /// Get the count of recorded values.
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+}
+
+/// Number of linear sub-buckets per binary magnitude in [`LatencyDigest`], derived from the
+/// configured significant-figure precision so each magnitude keeps that many decimal digits of
+/// resolution.
+fn sub_buckets_per_magnitude(significant_figures: u8) -> u64 {
+    10u64.saturating_pow(significant_figures as u32).max(1)
+}
+
+/// The magnitude (number of bits) of a value, i.e. the `m` such that `value` falls in
+/// `[2^(m-1), 2^m)`, with `0` itself assigned magnitude `0`.
+fn magnitude(value: u64) -> u32 {
+    64 - value.leading_zeros()
+}
+
+/// A latency/percentile aggregator backed by an HDR-histogram-style structure: each recorded
+/// value is bucketed by its magnitude (leading bit) plus a linear sub-bucket within that
+/// magnitude, sized by `significant_figures`. Unlike storing every raw sample, memory is bounded
+/// and independent of the number of samples recorded: the table holds
+/// `(floor(log2(max_trackable_value)) + 2) * 10^significant_figures` counters (one row per
+/// magnitude from `0` up to that of `max_trackable_value`), so only as many magnitude
+/// rows are allocated as are needed to cover `max_trackable_value`, not one per bit of `u64`. For
+/// example, tracking millisecond latencies up to an hour (`max_trackable_value` ~= 3.6e6) at 2
+/// significant figures takes on the order of tens of KB; picking a narrower range and/or fewer
+/// significant figures shrinks it further.
+#[derive(Debug, Clone)]
+pub struct LatencyDigest {
+    /// Linear sub-buckets per magnitude row.
+    sub_buckets: u64,
+    /// Values above this are clamped into the top row.
+    max_trackable_value: u64,
+    /// Per-(magnitude, sub-bucket) sample counts, flattened as `magnitude * sub_buckets +
+    /// sub_bucket`.
+    counts: Vec<u64>,
+    total_count: u64,
+    min: u64,
+    max: u64,
+}
+
+impl LatencyDigest {
+    /// Create a digest covering `[0, max_trackable_value]` with `significant_figures` (typically
+    /// 1-5) decimal digits of precision. Values above `max_trackable_value` are clamped into the
+    /// top bucket rather than growing the table.
+    pub fn new(max_trackable_value: u64, significant_figures: u8) -> Self {
+        let sub_buckets = sub_buckets_per_magnitude(significant_figures);
+        let row_count = magnitude(max_trackable_value.max(1)) as usize + 1;
+
+        Self {
+            sub_buckets,
+            max_trackable_value,
+            counts: vec![0; sub_buckets as usize * row_count],
+            total_count: 0,
+            min: u64::MAX,
+            max: 0,
+        }
+    }
+
+    /// Record a new observation. Values above `max_trackable_value` are clamped.
+    pub fn record(&mut self, value: u64) {
+        let value = value.min(self.max_trackable_value);
+
+        let index = self.bucket_index(value);
+        self.counts[index] += 1;
+        self.total_count += 1;
+        self.min = self.min.min(value);
+        self.max = self.max.max(value);
+    }
+
+    /// The smallest recorded value, or `0` if nothing has been recorded.
+    pub fn min(&self) -> u64 {
+        if self.total_count == 0 {
+            0
+        } else {
+            self.min
+        }
+    }
+
+    /// The largest recorded value, or `0` if nothing has been recorded.
+    pub fn max(&self) -> u64 {
+        self.max
+    }
+
+    /// The mean of all recorded values, approximated from bucket midpoints. `0.0` if nothing has
+    /// been recorded.
+    pub fn mean(&self) -> f64 {
+        if self.total_count == 0 {
+            return 0.0;
+        }
+
+        let sum: u64 = self
+            .counts
+            .iter()
+            .enumerate()
+            .map(|(index, &count)| self.bucket_midpoint(index) * count)
+            .sum();
+
+        sum as f64 / self.total_count as f64
+    }
+
+    /// The value at quantile `q` (e.g. `0.5`, `0.9`, `0.99`), approximated from the bucket whose
+    /// cumulative count first reaches `q * total_count`. `0` if nothing has been recorded.
+    pub fn percentile(&self, q: f64) -> u64 {
+        if self.total_count == 0 {
+            return 0;
+        }
+
+        let target = (q * self.total_count as f64).ceil() as u64;
+        let mut cumulative = 0;
+
+        for (index, &count) in self.counts.iter().enumerate() {
+            cumulative += count;
+
+            if cumulative >= target {
+                return self.bucket_midpoint(index);
+            }
+        }
+
+        self.max
+    }
+
+    /// Emit `min`/`max`/`mean`/p50/p90/p99 as fields on a single [`influxdb::WriteQuery`].
+    pub fn to_query(&self) -> influxdb::WriteQuery {
+        LatencyDigestFields {
+            time: crate::now_timestamp(),
+            min: self.min() as f64,
+            max: self.max() as f64,
+            mean: self.mean(),
+            p50: self.percentile(0.5) as f64,
+            p90: self.percentile(0.9) as f64,
+            p99: self.percentile(0.99) as f64,
+        }
+        .into_named_query()
+    }
+
+    fn bucket_index(&self, value: u64) -> usize {
+        let magnitude = magnitude(value) as usize;
+        let sub_bucket = self.sub_bucket(value, magnitude as u32);
+
+        magnitude * self.sub_buckets as usize + sub_bucket
+    }
+
+    /// The linear sub-bucket `value` falls into within its magnitude row.
+    fn sub_bucket(&self, value: u64, magnitude: u32) -> usize {
+        let Some(row_start) = magnitude.checked_sub(1).map(|m| 1u64 << m) else {
+            return 0; // magnitude 0 only contains the value 0.
+        };
+
+        let row_width = row_start; // [row_start, 2 * row_start) has the same width as row_start.
+        let sub_bucket_width = (row_width / self.sub_buckets).max(1);
+
+        (((value - row_start) / sub_bucket_width) as usize).min(self.sub_buckets as usize - 1)
+    }
+
+    /// The representative value (midpoint) of bucket `index`.
+    fn bucket_midpoint(&self, index: usize) -> u64 {
+        let sub_buckets = self.sub_buckets as usize;
+        let magnitude = (index / sub_buckets) as u32;
+        let sub_bucket = (index % sub_buckets) as u64;
+
+        let Some(row_start) = magnitude.checked_sub(1).map(|m| 1u64 << m) else {
+            return 0;
+        };
+
+        let sub_bucket_width = (row_start / self.sub_buckets).max(1);
+        row_start + sub_bucket * sub_bucket_width + sub_bucket_width / 2
+    }
+}
+
+#[derive(Debug, Clone, Copy, InfluxDbWriteable)]
+struct LatencyDigestFields {
+    time: influxdb::Timestamp,
+    min: f64,
+    max: f64,
+    mean: f64,
+    p50: f64,
+    p90: f64,
+    p99: f64,
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -53,4 +296,40 @@ mod tests {
             assert_eq!(running_avg.get(), avg);
         }
     }
+
+    #[test]
+    fn test_running_stats() {
+        let mut stats = RunningStats::default();
+        for i in 1..=100 {
+            stats.accept(i as f64);
+        }
+
+        let values: Vec<f64> = (1..=100).map(|i| i as f64).collect();
+        let mean = values.iter().sum::<f64>() / values.len() as f64;
+        let variance =
+            values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / values.len() as f64;
+
+        assert!((stats.mean() - mean).abs() < 1e-9);
+        assert!((stats.variance() - variance).abs() < 1e-9);
+        assert!((stats.stddev() - variance.sqrt()).abs() < 1e-9);
+        assert_eq!(stats.min(), 1.0);
+        assert_eq!(stats.max(), 100.0);
+        assert_eq!(stats.count(), 100);
+    }
+
+    #[test]
+    fn test_latency_digest_percentiles() {
+        let mut digest = LatencyDigest::new(1000, 3);
+
+        for value in 1..=1000u64 {
+            digest.record(value);
+        }
+
+        assert_eq!(digest.min(), 1);
+        assert_eq!(digest.max(), 1000);
+
+        // Bucketed, so only approximately accurate, but should be close to the true values.
+        assert!((digest.percentile(0.5) as i64 - 500).abs() <= 10);
+        assert!((digest.percentile(0.99) as i64 - 990).abs() <= 10);
+    }
 }